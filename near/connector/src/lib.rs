@@ -1,20 +1,24 @@
 use bitcoin::absolute::LockTime;
+use bitcoin::address::NetworkUnchecked;
 use bitcoin::blockdata::transaction::Transaction as BitcoinTransaction;
 use bitcoin::consensus::{deserialize, serialize};
 use bitcoin::hashes::Hash;
 use bitcoin::transaction::Version;
 use bitcoin::TxIn as BitcoinTxIn;
 use bitcoin::TxOut as BitcoinTxOut;
-use bitcoin::{sighash, Address, Amount, EcdsaSighashType, PublicKey};
+use bitcoin::{sighash, Address, Amount, EcdsaSighashType, PublicKey, Sequence, TapSighashType};
 use bitcoin_types::bitcoin_connector_events::BitcoinConnectorEvent;
-use bitcoin_types::bitcoin_connector_types::{NewTransferToBitcoin, Script, UTXO};
-use bitcoin_types::connector_args::{FinTransferArgs, SignRequest};
+use bitcoin_types::bitcoin_connector_types::{
+    BitcoinNetwork, InFlightTransfer, NewTransferToBitcoin, Script, UTXO,
+};
+use bitcoin_types::connector_args::{FinTransferArgs, SignRequest, SignatureScheme};
 use bitcoin_types::mpc_types::SignatureResponse;
 use btc_types::contract_args::ProofArgs;
 use btc_types::hash::H256;
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 use near_plugins::{
-    access_control, pause, AccessControlRole, AccessControllable, Pausable, Upgradable,
+    access_control, access_control_any, pause, AccessControlRole, AccessControllable, Pausable,
+    Upgradable,
 };
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LookupMap, LookupSet, Vector};
@@ -23,7 +27,8 @@ use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::PanicOnDefault;
 use near_sdk::{
-    env, near, require, AccountId, BorshStorageKey, Gas, Promise, PromiseError, PromiseOrValue,
+    env, near, require, AccountId, BorshStorageKey, Gas, NearToken, Promise, PromiseError,
+    PromiseOrValue, PromiseResult,
 };
 use std::default::Default;
 use std::str::FromStr;
@@ -37,11 +42,39 @@ const SIGN_TRANSFER_CALLBACK_GAS: Gas = Gas::from_tgas(5);
 
 const SIGN_PATH: &str = "bitcoin-connector-1";
 
+// Weight estimates for a P2WPKH-only transaction, in vBytes.
+const TX_OVERHEAD_VBYTES: u64 = 10;
+const P2WPKH_INPUT_VBYTES: u64 = 68;
+const P2WPKH_OUTPUT_VBYTES: u64 = 31;
+// Outputs below this value are not economical to spend, so leftover change
+// smaller than this is folded into the fee instead of creating a new UTXO.
+const DUST_THRESHOLD: u64 = 546;
+
 #[derive(BorshSerialize, BorshStorageKey)]
 enum StorageKey {
     FinalisedTransfers,
     UTXOs,
     NewTransfers,
+    InFlightTransfers,
+}
+
+/// Result of running coin selection for a single payout: the inputs to spend
+/// and, if the leftover is at or above the dust threshold, the amount to send back
+/// to `self.bitcoin_pk` as change.
+struct CoinSelection {
+    utxos: Vec<UTXO>,
+    change: Option<u64>,
+}
+
+impl From<BitcoinNetwork> for bitcoin::Network {
+    fn from(network: BitcoinNetwork) -> Self {
+        match network {
+            BitcoinNetwork::Mainnet => bitcoin::Network::Bitcoin,
+            BitcoinNetwork::Testnet => bitcoin::Network::Testnet,
+            BitcoinNetwork::Signet => bitcoin::Network::Signet,
+            BitcoinNetwork::Regtest => bitcoin::Network::Regtest,
+        }
+    }
 }
 
 #[derive(AccessControlRole, Deserialize, Serialize, Copy, Clone)]
@@ -66,6 +99,7 @@ pub enum Role {
 ))]
 pub struct BitcoinConnector {
     pub bitcoin_pk: String,
+    pub bitcoin_taproot_pk: String,
     pub omni_btc: AccountId,
     pub finalised_transfers: LookupSet<H256>,
     pub confirmations: u64,
@@ -75,6 +109,17 @@ pub struct BitcoinConnector {
     pub new_transfers: LookupMap<u64, NewTransferToBitcoin>,
     pub min_nonce: u64,
     pub last_nonce: u64,
+    pub fee_rate: u64,
+    pub network: BitcoinNetwork,
+    /// rust-miniscript output descriptor (e.g. a `wsh`/`tr` multisig or
+    /// timelocked recovery policy) whose scripts are also recognized as the
+    /// connector's own custody in `fin_transfer_callback`.
+    pub custody_descriptor: String,
+    /// Signed payout transactions that have been broadcast but not yet
+    /// observed as confirmed, keyed by normalized txid, so `bump_fee` can
+    /// rebuild and re-sign them with a higher fee without double-spending
+    /// their inputs.
+    pub in_flight_transfers: LookupMap<H256, InFlightTransfer>,
 }
 
 #[ext_contract(ext_omni_bitcoin)]
@@ -99,13 +144,20 @@ impl BitcoinConnector {
     #[init]
     pub fn new(
         connector_bitcoin_public_key: String,
+        connector_bitcoin_taproot_public_key: String,
         omni_btc: AccountId,
         confirmations: u64,
         btc_light_client: AccountId,
         mpc_signer: AccountId,
+        fee_rate: u64,
+        network: BitcoinNetwork,
+        custody_descriptor: String,
     ) -> Self {
+        Self::validate_custody_descriptor(&custody_descriptor);
+
         Self {
             bitcoin_pk: connector_bitcoin_public_key,
+            bitcoin_taproot_pk: connector_bitcoin_taproot_public_key,
             omni_btc,
             finalised_transfers: LookupSet::new(StorageKey::FinalisedTransfers),
             confirmations,
@@ -115,9 +167,24 @@ impl BitcoinConnector {
             new_transfers: LookupMap::new(StorageKey::NewTransfers),
             min_nonce: 0,
             last_nonce: 0,
+            fee_rate,
+            network,
+            custody_descriptor,
+            in_flight_transfers: LookupMap::new(StorageKey::InFlightTransfers),
         }
     }
 
+    #[access_control_any(roles(Role::DAO))]
+    pub fn set_fee_rate(&mut self, fee_rate: u64) {
+        self.fee_rate = fee_rate;
+    }
+
+    #[access_control_any(roles(Role::DAO))]
+    pub fn set_custody_descriptor(&mut self, custody_descriptor: String) {
+        Self::validate_custody_descriptor(&custody_descriptor);
+        self.custody_descriptor = custody_descriptor;
+    }
+
     pub fn fin_transfer(&mut self, #[serializer(borsh)] args: FinTransferArgs) -> Promise {
         let tx: BitcoinTransaction = deserialize(&args.tx_raw).unwrap();
 
@@ -149,29 +216,45 @@ impl BitcoinConnector {
         let tx: BitcoinTransaction = deserialize(&tx_raw).unwrap();
         let tx_id = Self::get_tx_id(&tx);
 
+        let custody_script_pubkeys = self.custody_script_pubkeys();
+
         let mut value = 0;
         let mut recipient = None;
         for (i, tx_output) in tx.output.into_iter().enumerate() {
-            let script: Script =
-                Script::from_bytes(tx_output.script_pubkey.as_bytes().to_vec()).unwrap();
-            match script.clone() {
-                Script::V0P2wpkh(pk) => {
-                    if pk == self.bitcoin_pk {
-                        value += tx_output.value.to_sat();
-                        self.utxos.push(&UTXO {
-                            txid: tx_id.clone(),
-                            vout: i as u32,
-                            value: tx_output.value.clone().to_sat(),
-                            script_pubkey: script.clone(),
-                        });
-                    }
+            let script_raw = tx_output.script_pubkey.as_bytes().to_vec();
+            let sats = tx_output.value.to_sat();
+
+            let deposit_script = match Script::from_bytes(script_raw.clone()) {
+                Ok(Script::V0P2wpkh(pk)) if pk == self.bitcoin_pk => Some(Script::V0P2wpkh(pk)),
+                Ok(Script::V1P2tr(x_only_pk)) if x_only_pk == self.bitcoin_taproot_pk => {
+                    Some(Script::V1P2tr(x_only_pk))
                 }
-                Script::OpReturn(account) => {
+                Ok(Script::OpReturn(account)) => {
                     if recipient != None {
                         panic!("Tx should contain exactly one OP_RETURN script");
                     }
-                    recipient = Some(account)
+                    recipient = Some(account);
+                    None
+                }
+                // Not one of the connector's own key-path scripts: recognize it
+                // as a deposit only if the custody descriptor also produces it.
+                _ if custody_script_pubkeys
+                    .iter()
+                    .any(|script_pubkey| script_pubkey.as_bytes() == script_raw.as_slice()) =>
+                {
+                    Some(Script::Custody(script_raw))
                 }
+                _ => None,
+            };
+
+            if let Some(script_pubkey) = deposit_script {
+                value += sats;
+                self.utxos.push(&UTXO {
+                    txid: tx_id.clone(),
+                    vout: i as u32,
+                    value: sats,
+                    script_pubkey,
+                });
             }
         }
 
@@ -187,47 +270,213 @@ impl BitcoinConnector {
         }
     }
 
+    /// Drains up to `batch_size` consecutive pending transfers starting at
+    /// `self.min_nonce` into a single Bitcoin transaction, fans out one MPC
+    /// signing request per input and joins them, then assembles the fully
+    /// signed transaction once every signature is back.
     #[payable]
-    pub fn sign(&mut self) -> Promise {
-        let (unsigned_tx, utxo) = self.get_unsigned_tx();
-        let msg_to_sign: Vec<u8> = self.sign_input(&unsigned_tx, &utxo, 0);
-        let ser_tx = serialize(&unsigned_tx);
+    pub fn sign(&mut self, batch_size: u64) -> Promise {
+        let (unsigned_tx, utxos, nonces) = self.get_unsigned_tx(batch_size);
+        self.request_signatures(unsigned_tx, utxos, nonces, self.fee_rate, None)
+    }
 
-        ext_signer::ext(self.mpc_signer.clone())
-            .with_static_gas(MPC_SIGNING_GAS)
-            .with_attached_deposit(env::attached_deposit())
-            .sign(SignRequest {
-                payload: msg_to_sign.clone().try_into().unwrap(),
-                path: SIGN_PATH.to_owned(),
-                key_version: 0,
+    /// Rebuilds an in-flight payout transaction with `sequence` set to the
+    /// RBF-signaling value and a higher absolute fee (reusing its original
+    /// inputs, pulling in more via coin selection if they no longer cover
+    /// the raised fee), then re-signs it and emits a replacement
+    /// `SignTransferEvent`. The original `in_flight_transfers` entry is
+    /// removed up front so its inputs can't be reused by a concurrent
+    /// `sign()`/`bump_fee()` call.
+    #[payable]
+    #[access_control_any(roles(Role::DAO))]
+    pub fn bump_fee(&mut self, tx_id: H256, new_fee_rate: u64) -> Promise {
+        let in_flight = self
+            .in_flight_transfers
+            .get(&tx_id)
+            .expect("No such in-flight transfer");
+        require!(
+            new_fee_rate > in_flight.fee_rate,
+            "Replacement fee rate must exceed the original (BIP125)"
+        );
+        let in_flight = self.in_flight_transfers.remove(&tx_id).unwrap();
+        let old_tx: BitcoinTransaction = deserialize(&in_flight.tx_raw).unwrap();
+        let num_payout_outputs = in_flight.nonces.len();
+        let payout_outputs: Vec<BitcoinTxOut> = old_tx.output[..num_payout_outputs].to_vec();
+        let target: u64 = payout_outputs.iter().map(|out| out.value.to_sat()).sum();
+
+        let mut utxos = in_flight.utxos;
+        let mut input_value: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+        let mut fee = Self::estimate_fee(utxos.len(), num_payout_outputs + 1, new_fee_rate);
+
+        while input_value < target + fee {
+            let mut extra = self.get_utxo(target + fee - input_value, new_fee_rate, num_payout_outputs);
+            input_value += extra.utxos.iter().map(|utxo| utxo.value).sum::<u64>();
+            utxos.append(&mut extra.utxos);
+            fee = Self::estimate_fee(utxos.len(), num_payout_outputs + 1, new_fee_rate);
+        }
+
+        let remainder = input_value - target - fee;
+        let change = if remainder >= DUST_THRESHOLD {
+            Some(remainder)
+        } else {
+            None
+        };
+
+        let input = utxos
+            .iter()
+            .map(|utxo| BitcoinTxIn {
+                previous_output: bitcoin::OutPoint {
+                    txid: bitcoin::Txid::from_byte_array(utxo.txid.clone().0),
+                    vout: utxo.vout,
+                },
+                script_sig: Default::default(),
+                // Signal replaceability (BIP125) so the bumped transaction can
+                // evict the stuck one it replaces from mempools.
+                sequence: Sequence(0xfffffffd),
+                witness: Default::default(),
             })
+            .collect();
+
+        let mut output = payout_outputs;
+        if let Some(change) = change {
+            let change_pubkey = PublicKey::from_str(&self.bitcoin_pk).unwrap();
+            output.push(BitcoinTxOut {
+                value: Amount::from_sat(change),
+                script_pubkey: bitcoin::ScriptBuf::new_p2wpkh(
+                    &change_pubkey.wpubkey_hash().expect("compressed pubkey required"),
+                ),
+            });
+        }
+
+        let unsigned_tx = BitcoinTransaction {
+            version: Version(2),
+            lock_time: LockTime::ZERO,
+            input,
+            output,
+        };
+
+        self.request_signatures(
+            unsigned_tx,
+            utxos,
+            in_flight.nonces,
+            new_fee_rate,
+            Some(hex::encode(tx_id.0)),
+        )
+    }
+
+    /// Verifies a broadcast payout transaction's on-chain inclusion (mirrors
+    /// `fin_transfer`) and, once confirmed, clears its `in_flight_transfers`
+    /// entry so a fee-bumped replacement can no longer double-spend it.
+    pub fn fin_payout(&mut self, #[serializer(borsh)] args: FinTransferArgs) -> Promise {
+        let tx: BitcoinTransaction = deserialize(&args.tx_raw).unwrap();
+        let tx_id = Self::get_tx_id(&tx);
+
+        let proof_args = ProofArgs {
+            tx_id: tx_id.clone(),
+            tx_block_blockhash: args.tx_block_blockhash,
+            tx_index: args.tx_index,
+            merkle_proof: args.merkle_proof,
+            confirmations: self.confirmations.clone(),
+        };
+
+        ext_btc_light_client::ext(self.btc_light_client.clone())
+            .with_static_gas(VERIFY_TX_GAS)
+            .verify_transaction_inclusion(proof_args)
             .then(
                 Self::ext(env::current_account_id())
-                    .with_static_gas(SIGN_TRANSFER_CALLBACK_GAS)
-                    .sign_callback(ser_tx),
+                    .with_static_gas(FT_TRANSFER_CALLBACK_GAS)
+                    .fin_payout_callback(tx_id),
             )
     }
 
+    #[private]
+    pub fn fin_payout_callback(
+        &mut self,
+        #[callback_result] call_result: Result<bool, PromiseError>,
+        tx_id: H256,
+    ) {
+        require!(call_result.unwrap(), "Failed to verify proof");
+        let in_flight = self
+            .in_flight_transfers
+            .remove(&tx_id)
+            .expect("No such in-flight transfer");
+
+        // The change output, if any, follows the fixed payout outputs (see
+        // `get_unsigned_tx`/`bump_fee`) and is always paid back to
+        // `self.bitcoin_pk` as P2WPKH; re-add it as a spendable UTXO now that
+        // the transaction has confirmed, the same way `fin_transfer_callback`
+        // does for deposits.
+        let tx: BitcoinTransaction = deserialize(&in_flight.tx_raw).unwrap();
+        if let Some(change_output) = tx.output.get(in_flight.nonces.len()) {
+            self.utxos.push(&UTXO {
+                txid: tx_id,
+                vout: in_flight.nonces.len() as u32,
+                value: change_output.value.to_sat(),
+                script_pubkey: Script::V0P2wpkh(self.bitcoin_pk.clone()),
+            });
+        }
+    }
+
+    /// Joined-promise callback: since the batch size is dynamic, the
+    /// per-input `SignatureResponse`s can't be bound with `#[callback_result]`
+    /// and are instead read off the raw promise results in join order. The
+    /// spent `utxos` (same order as `unsigned_tx.input`) determine whether
+    /// each input's witness is a lone Schnorr signature (P2TR) or a
+    /// signature plus pubkey (P2WPKH). The fully signed transaction is kept
+    /// as an `in_flight_transfers` entry (keyed by its normalized txid) so it
+    /// can later be fee-bumped via `bump_fee`.
     #[private]
     pub fn sign_callback(
         &mut self,
-        #[callback_result] call_result: Result<SignatureResponse, PromiseError>,
         ser_tx: Vec<u8>,
+        #[serializer(borsh)] utxos: Vec<UTXO>,
+        fee_rate: u64,
+        #[serializer(borsh)] nonces: Vec<u64>,
+        replaces: Option<String>,
     ) {
         let mut unsigned_tx: BitcoinTransaction = deserialize(&ser_tx).unwrap();
-
-        let signature = call_result.unwrap();
-        let sig_raw = signature.to_bytes();
-        unsigned_tx.input[0].witness.push(sig_raw);
-
         let public_key = PublicKey::from_str(&self.bitcoin_pk).unwrap();
-        unsigned_tx.input[0].witness.push(public_key.to_bytes());
 
+        for i in 0..env::promise_results_count() {
+            let signature: SignatureResponse = match env::promise_result(i) {
+                PromiseResult::Successful(bytes) => near_sdk::serde_json::from_slice(&bytes)
+                    .expect("Failed to deserialize signature response"),
+                PromiseResult::Failed => panic!("Failed to sign input {}", i),
+            };
+
+            let input = &mut unsigned_tx.input[i as usize];
+            match &utxos[i as usize].script_pubkey {
+                Script::V1P2tr(_) => {
+                    input.witness.push(signature.to_bytes());
+                }
+                Script::V0P2wpkh(_) => {
+                    input.witness.push(signature.to_bytes());
+                    input.witness.push(public_key.to_bytes());
+                }
+                Script::OpReturn(_) => {
+                    unreachable!("OP_RETURN outputs are never added to self.utxos")
+                }
+                Script::Custody(_) => panic!("Custody-descriptor UTXOs are not signable by sign()"),
+            }
+        }
+
+        let tx_id = Self::get_tx_id(&unsigned_tx);
         let tx_hex_string = hex::encode(serialize(&unsigned_tx));
 
+        self.in_flight_transfers.insert(
+            &tx_id,
+            &InFlightTransfer {
+                tx_raw: serialize(&unsigned_tx),
+                utxos,
+                fee_rate,
+                nonces,
+            },
+        );
+
         env::log_str(
             &BitcoinConnectorEvent::SignTransferEvent {
                 bitcoin_tx_hex: tx_hex_string,
+                replaces_tx_hex: replaces,
             }
             .to_log_string(),
         );
@@ -240,70 +489,401 @@ impl BitcoinConnector {
         H256::from(tx_id.to_byte_array())
     }
 
+    /// Fans out one MPC signing request per input and joins them, then
+    /// assembles and persists the fully signed transaction once every
+    /// signature is back. Shared between `sign()` and `bump_fee()`; `replaces`
+    /// is the hex-encoded normalized txid of the in-flight transaction this
+    /// one replaces, if this is a fee bump.
+    fn request_signatures(
+        &self,
+        unsigned_tx: BitcoinTransaction,
+        utxos: Vec<UTXO>,
+        nonces: Vec<u64>,
+        fee_rate: u64,
+        replaces: Option<String>,
+    ) -> Promise {
+        let sighashes = self.sign_inputs(&unsigned_tx, &utxos);
+        let ser_tx = serialize(&unsigned_tx);
+
+        let deposit_per_input = NearToken::from_yoctonear(
+            env::attached_deposit().as_yoctonear() / sighashes.len() as u128,
+        );
+
+        let joined_signing_promise = sighashes
+            .into_iter()
+            .map(|(sighash, scheme)| {
+                ext_signer::ext(self.mpc_signer.clone())
+                    .with_static_gas(MPC_SIGNING_GAS)
+                    .with_attached_deposit(deposit_per_input)
+                    .sign(SignRequest {
+                        payload: sighash.try_into().unwrap(),
+                        path: SIGN_PATH.to_owned(),
+                        key_version: 0,
+                        scheme,
+                    })
+            })
+            .reduce(Promise::and)
+            .expect("Batch must sign at least one input");
+
+        let callback_gas = SIGN_TRANSFER_CALLBACK_GAS
+            .checked_mul(utxos.len() as u64)
+            .unwrap();
+
+        joined_signing_promise.then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(callback_gas)
+                .sign_callback(ser_tx, utxos, fee_rate, nonces, replaces),
+        )
+    }
+
+    fn sign_inputs(
+        &self,
+        unsigned_tx: &BitcoinTransaction,
+        utxos: &[UTXO],
+    ) -> Vec<(Vec<u8>, SignatureScheme)> {
+        let prevouts: Vec<BitcoinTxOut> = utxos
+            .iter()
+            .map(|utxo| BitcoinTxOut {
+                value: Amount::from_sat(utxo.value),
+                script_pubkey: Self::script_pubkey(&utxo.script_pubkey),
+            })
+            .collect();
+
+        utxos
+            .iter()
+            .enumerate()
+            .map(|(input_index, utxo)| self.sign_input(unsigned_tx, &prevouts, utxo, input_index))
+            .collect()
+    }
+
     fn sign_input(
-        &mut self,
+        &self,
         unsigned_tx: &BitcoinTransaction,
+        prevouts: &[BitcoinTxOut],
         utxo: &UTXO,
         input_index: usize,
-    ) -> Vec<u8> {
-        let public_key = PublicKey::from_str(&self.bitcoin_pk).unwrap();
-
+    ) -> (Vec<u8>, SignatureScheme) {
         let mut cache = sighash::SighashCache::new(unsigned_tx);
-        let sighash = cache
-            .p2wpkh_signature_hash(
-                input_index,
-                &public_key.p2wpkh_script_code().unwrap(),
-                Amount::from_sat(utxo.value),
-                EcdsaSighashType::All,
-            )
-            .expect("failed to compute sighash");
 
-        sighash.to_byte_array().to_vec()
+        match &utxo.script_pubkey {
+            Script::V1P2tr(_) => {
+                let sighash = cache
+                    .taproot_key_spend_signature_hash(
+                        input_index,
+                        &sighash::Prevouts::All(prevouts),
+                        TapSighashType::Default,
+                    )
+                    .expect("failed to compute taproot sighash");
+
+                (sighash.to_byte_array().to_vec(), SignatureScheme::Schnorr)
+            }
+            Script::V0P2wpkh(_) => {
+                let public_key = PublicKey::from_str(&self.bitcoin_pk).unwrap();
+                let sighash = cache
+                    .p2wpkh_signature_hash(
+                        input_index,
+                        &public_key.p2wpkh_script_code().unwrap(),
+                        Amount::from_sat(utxo.value),
+                        EcdsaSighashType::All,
+                    )
+                    .expect("failed to compute sighash");
+
+                (sighash.to_byte_array().to_vec(), SignatureScheme::Ecdsa)
+            }
+            Script::OpReturn(_) => {
+                unreachable!("OP_RETURN outputs are never added to self.utxos")
+            }
+            Script::Custody(_) => panic!("Custody-descriptor UTXOs are not signable by sign()"),
+        }
+    }
+
+    /// Reconstructs the actual on-chain `scriptPubkey` bytes for a UTXO from
+    /// the connector's compact `Script` representation (a pubkey hash or
+    /// x-only key, not the script itself).
+    fn script_pubkey(script: &Script) -> bitcoin::ScriptBuf {
+        match script {
+            Script::V0P2wpkh(pubkey_hash_hex) => {
+                let pubkey_hash = bitcoin::WPubkeyHash::from_slice(
+                    &hex::decode(pubkey_hash_hex).expect("invalid pubkey hash hex"),
+                )
+                .expect("invalid pubkey hash");
+                bitcoin::ScriptBuf::new_p2wpkh(&pubkey_hash)
+            }
+            Script::V1P2tr(x_only_pk_hex) => {
+                let x_only_pk = bitcoin::XOnlyPublicKey::from_slice(
+                    &hex::decode(x_only_pk_hex).expect("invalid x-only pubkey hex"),
+                )
+                .expect("invalid x-only pubkey");
+                let tweaked_pk = bitcoin::key::TweakedPublicKey::dangerous_assume_tweaked(x_only_pk);
+                bitcoin::ScriptBuf::new_p2tr_tweaked(tweaked_pk)
+            }
+            Script::OpReturn(_) => panic!("OP_RETURN has no spendable scriptPubkey"),
+            Script::Custody(_) => panic!("Custody-descriptor UTXOs are not signable by sign()"),
+        }
     }
 
-    fn get_unsigned_tx(&mut self) -> (BitcoinTransaction, UTXO) {
-        let utxo = self.get_utxo();
-        let new_transfer_data = self.new_transfers.get(&self.min_nonce).unwrap();
-        self.new_transfers.remove(&self.min_nonce);
-        self.min_nonce += 1;
+    fn parse_custody_descriptor(custody_descriptor: &str) -> miniscript::Descriptor<bitcoin::PublicKey> {
+        miniscript::Descriptor::from_str(custody_descriptor).expect("invalid custody descriptor")
+    }
 
-        let txin = BitcoinTxIn {
-            previous_output: bitcoin::OutPoint {
-                txid: bitcoin::Txid::from_byte_array(utxo.txid.clone().0),
-                vout: utxo.vout.clone(),
-            },
-            script_sig: Default::default(),
-            sequence: Default::default(),
-            witness: Default::default(),
-        };
+    fn validate_custody_descriptor(custody_descriptor: &str) {
+        Self::parse_custody_descriptor(custody_descriptor);
+    }
 
-        let recipient_address = Address::from_str(&new_transfer_data.recipient_on_bitcoin).unwrap();
-        let recipient_address = recipient_address.assume_checked();
+    /// The set of scriptPubkeys produced by `self.custody_descriptor`,
+    /// recognized as the connector's own custody in addition to the lone
+    /// `bitcoin_pk`/`bitcoin_taproot_pk` key-path scripts.
+    fn custody_script_pubkeys(&self) -> Vec<bitcoin::ScriptBuf> {
+        vec![Self::parse_custody_descriptor(&self.custody_descriptor).script_pubkey()]
+    }
 
-        let txout = BitcoinTxOut {
-            value: Amount::from_sat(new_transfer_data.value),
-            script_pubkey: recipient_address.script_pubkey(),
-        };
+    /// Drains up to `batch_size` consecutive pending transfers starting at
+    /// `self.min_nonce` (stopping early if a nonce is missing) into one
+    /// transaction with one `TxOut` per recipient, spending the inputs coin
+    /// selection picks, plus a change output to `self.bitcoin_pk` only when
+    /// the selection leaves change.
+    fn get_unsigned_tx(&mut self, batch_size: u64) -> (BitcoinTransaction, Vec<UTXO>, Vec<u64>) {
+        let mut transfers = vec![];
+        let mut nonces = vec![];
+        for nonce in self.min_nonce..self.min_nonce + batch_size {
+            match self.new_transfers.get(&nonce) {
+                Some(transfer) => {
+                    self.new_transfers.remove(&nonce);
+                    transfers.push(transfer);
+                    nonces.push(nonce);
+                }
+                None => break,
+            }
+        }
+        require!(!transfers.is_empty(), "No pending transfers to sign");
+        self.min_nonce += transfers.len() as u64;
+
+        let target: u64 = transfers.iter().map(|transfer| transfer.value).sum();
+        let selection = self.get_utxo(target, self.fee_rate, transfers.len());
+
+        let input = selection
+            .utxos
+            .iter()
+            .map(|utxo| BitcoinTxIn {
+                previous_output: bitcoin::OutPoint {
+                    txid: bitcoin::Txid::from_byte_array(utxo.txid.clone().0),
+                    vout: utxo.vout.clone(),
+                },
+                script_sig: Default::default(),
+                sequence: Default::default(),
+                witness: Default::default(),
+            })
+            .collect();
+
+        let mut output: Vec<BitcoinTxOut> = transfers
+            .iter()
+            .map(|transfer| {
+                let recipient_address: Address<NetworkUnchecked> =
+                    Address::from_str(&transfer.recipient_on_bitcoin).unwrap();
+                let recipient_address = recipient_address
+                    .require_network(self.network.into())
+                    .expect("Recipient address is for the wrong Bitcoin network");
+                BitcoinTxOut {
+                    value: Amount::from_sat(transfer.value),
+                    script_pubkey: recipient_address.script_pubkey(),
+                }
+            })
+            .collect();
+
+        if let Some(change) = selection.change {
+            let change_pubkey = PublicKey::from_str(&self.bitcoin_pk).unwrap();
+            output.push(BitcoinTxOut {
+                value: Amount::from_sat(change),
+                script_pubkey: bitcoin::ScriptBuf::new_p2wpkh(
+                    &change_pubkey.wpubkey_hash().expect("compressed pubkey required"),
+                ),
+            });
+        }
 
         let unsigned_tx = BitcoinTransaction {
             version: Version(2),
             lock_time: LockTime::ZERO,
-            input: vec![txin],
-            output: vec![txout],
+            input,
+            output,
         };
 
-        (unsigned_tx, utxo)
+        (unsigned_tx, selection.utxos, nonces)
+    }
+
+    /// Selects UTXOs covering `target` plus the miner fee for `fee_rate`
+    /// (sat/vByte) and `num_payout_outputs` recipients, removing them from
+    /// `self.utxos`. Tries Branch-and-Bound first for an exact (or
+    /// near-exact, no-change) match, falling back to greedy accumulation of
+    /// the largest UTXOs when no such match exists.
+    fn get_utxo(&mut self, target: u64, fee_rate: u64, num_payout_outputs: usize) -> CoinSelection {
+        let mut candidates: Vec<UTXO> = (0..self.utxos.len())
+            .map(|i| self.utxos.get(i).unwrap())
+            .filter(|utxo| !matches!(utxo.script_pubkey, Script::Custody(_)))
+            .collect();
+        candidates.sort_by(|a, b| b.value.cmp(&a.value));
+
+        let selection =
+            Self::branch_and_bound_select(&candidates, target, fee_rate, num_payout_outputs)
+                .or_else(|| Self::greedy_select(&candidates, target, fee_rate, num_payout_outputs))
+                .expect("Not enough UTXOs to cover the transfer");
+
+        for utxo in &selection.utxos {
+            self.remove_utxo(&utxo.txid, utxo.vout);
+        }
+
+        selection
+    }
+
+    fn remove_utxo(&mut self, txid: &H256, vout: u32) -> UTXO {
+        let index = (0..self.utxos.len())
+            .find(|&i| {
+                let utxo = self.utxos.get(i).unwrap();
+                &utxo.txid == txid && utxo.vout == vout
+            })
+            .expect("UTXO no longer available");
+        self.utxos.swap_remove(index)
+    }
+
+    fn estimate_fee(num_inputs: usize, num_outputs: usize, fee_rate: u64) -> u64 {
+        (TX_OVERHEAD_VBYTES
+            + P2WPKH_INPUT_VBYTES * num_inputs as u64
+            + P2WPKH_OUTPUT_VBYTES * num_outputs as u64)
+            * fee_rate
+    }
+
+    /// Depth-first include/exclude search over `candidates` (sorted by value
+    /// descending) for a selection landing in
+    /// `[target+fee, target+fee+cost_of_change]`, returning the first match
+    /// found in that order. A match has no change output: whatever it
+    /// selects above `target+fee` goes to the fee.
+    fn branch_and_bound_select(
+        candidates: &[UTXO],
+        target: u64,
+        fee_rate: u64,
+        num_payout_outputs: usize,
+    ) -> Option<CoinSelection> {
+        let cost_of_change = P2WPKH_OUTPUT_VBYTES * fee_rate;
+        let total_value: u64 = candidates.iter().map(|utxo| utxo.value).sum();
+
+        fn search(
+            candidates: &[UTXO],
+            index: usize,
+            selected: &mut Vec<usize>,
+            selected_sum: u64,
+            remaining_sum: u64,
+            target: u64,
+            fee_rate: u64,
+            num_payout_outputs: usize,
+            cost_of_change: u64,
+        ) -> Option<Vec<usize>> {
+            let fee = BitcoinConnector::estimate_fee(selected.len(), num_payout_outputs, fee_rate);
+            let target_with_fee = target + fee;
+
+            if selected_sum > target_with_fee + cost_of_change {
+                return None;
+            }
+            if selected_sum >= target_with_fee {
+                return Some(selected.clone());
+            }
+            if index >= candidates.len() || selected_sum + remaining_sum < target_with_fee {
+                return None;
+            }
+
+            let utxo = &candidates[index];
+            let remaining_sum = remaining_sum - utxo.value;
+
+            // Include candidates[index].
+            selected.push(index);
+            if let Some(found) = search(
+                candidates,
+                index + 1,
+                selected,
+                selected_sum + utxo.value,
+                remaining_sum,
+                target,
+                fee_rate,
+                num_payout_outputs,
+                cost_of_change,
+            ) {
+                return Some(found);
+            }
+            selected.pop();
+
+            // Exclude candidates[index].
+            search(
+                candidates,
+                index + 1,
+                selected,
+                selected_sum,
+                remaining_sum,
+                target,
+                fee_rate,
+                num_payout_outputs,
+                cost_of_change,
+            )
+        }
+
+        let indices = search(
+            candidates,
+            0,
+            &mut vec![],
+            0,
+            total_value,
+            target,
+            fee_rate,
+            num_payout_outputs,
+            cost_of_change,
+        )?;
+
+        let utxos: Vec<UTXO> = indices.into_iter().map(|i| candidates[i].clone()).collect();
+
+        Some(CoinSelection {
+            utxos,
+            change: None,
+        })
     }
 
-    fn get_utxo(&mut self) -> UTXO {
-        let mut max_j = 0;
-        for i in 1..self.utxos.len() {
-            if self.utxos.get(i).unwrap().value > self.utxos.get(max_j).unwrap().value {
-                max_j = i;
+    /// Accumulates the largest UTXOs until `target` plus fee is covered,
+    /// assuming a change output first and folding the remainder into the fee
+    /// if it would end up below the dust threshold.
+    fn greedy_select(
+        candidates: &[UTXO],
+        target: u64,
+        fee_rate: u64,
+        num_payout_outputs: usize,
+    ) -> Option<CoinSelection> {
+        let mut selected = vec![];
+        let mut selected_sum = 0u64;
+
+        for utxo in candidates {
+            selected.push(utxo.clone());
+            selected_sum += utxo.value;
+
+            let fee_with_change =
+                Self::estimate_fee(selected.len(), num_payout_outputs + 1, fee_rate);
+            if selected_sum >= target + fee_with_change {
+                let remainder = selected_sum - target - fee_with_change;
+                let change = if remainder >= DUST_THRESHOLD {
+                    Some(remainder)
+                } else {
+                    None
+                };
+                return Some(CoinSelection {
+                    utxos: selected,
+                    change,
+                });
             }
         }
 
-        self.utxos.swap_remove(max_j)
+        let fee_no_change = Self::estimate_fee(selected.len(), num_payout_outputs, fee_rate);
+        if selected_sum >= target + fee_no_change {
+            return Some(CoinSelection {
+                utxos: selected,
+                change: None,
+            });
+        }
+
+        None
     }
 }
 
@@ -341,3 +921,60 @@ impl FungibleTokenReceiver for BitcoinConnector {
         PromiseOrValue::Value(U128(0))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(value: u64) -> UTXO {
+        UTXO {
+            txid: H256::from([0u8; 32]),
+            vout: 0,
+            value,
+            script_pubkey: Script::V0P2wpkh(String::new()),
+        }
+    }
+
+    #[test]
+    fn test_branch_and_bound_exact_match() {
+        // A single UTXO worth exactly target + fee(1 input, 1 output) should
+        // be picked by BnB with no change output.
+        let candidates = vec![utxo(10109)];
+
+        let selection =
+            BitcoinConnector::branch_and_bound_select(&candidates, 10000, 1, 1).unwrap();
+
+        assert_eq!(selection.utxos.len(), 1);
+        assert_eq!(selection.utxos[0].value, 10109);
+        assert_eq!(selection.change, None);
+    }
+
+    #[test]
+    fn test_branch_and_bound_falls_back_to_greedy() {
+        // No subset of {6000, 5000} lands in BnB's
+        // [target+fee, target+fee+cost_of_change] window, so BnB must fail
+        // and greedy accumulation (which takes both, leaving change) is the
+        // one that actually ships a transaction.
+        let candidates = vec![utxo(6000), utxo(5000)];
+
+        assert!(BitcoinConnector::branch_and_bound_select(&candidates, 9000, 1, 1).is_none());
+
+        let selection = BitcoinConnector::greedy_select(&candidates, 9000, 1, 1).unwrap();
+
+        assert_eq!(selection.utxos.len(), 2);
+        assert_eq!(selection.change, Some(1792));
+    }
+
+    #[test]
+    fn test_greedy_select_folds_dust_into_fee() {
+        // Greedy's leftover (160 sats) is below DUST_THRESHOLD, so it must be
+        // folded into the fee rather than create an uneconomical change
+        // output.
+        let candidates = vec![utxo(9300)];
+
+        let selection = BitcoinConnector::greedy_select(&candidates, 9000, 1, 1).unwrap();
+
+        assert_eq!(selection.utxos.len(), 1);
+        assert_eq!(selection.change, None);
+    }
+}
@@ -11,6 +11,9 @@ pub enum BitcoinConnectorEvent {
     },
     SignTransferEvent {
         bitcoin_tx_hex: String,
+        /// Hex-encoded normalized txid of the in-flight transaction this one
+        /// replaces via RBF, if any.
+        replaces_tx_hex: Option<String>,
     },
 }
 
@@ -1,8 +1,20 @@
 use crate::bitcoin_connector_types::Script::OpReturn;
 use btc_types::hash::H256;
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::AccountId;
 
+/// Bitcoin network the connector is configured for. Kept separate from
+/// `bitcoin::Network` so this crate doesn't need to depend on `bitcoin`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, BorshSerialize, BorshDeserialize, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum BitcoinNetwork {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, BorshSerialize, BorshDeserialize)]
 pub struct UTXO {
     pub txid: H256,
@@ -18,26 +30,53 @@ pub struct NewTransferToBitcoin {
     pub value: u64,
 }
 
+/// A payout transaction that has been signed and emitted but not yet
+/// observed as confirmed, kept around so `bump_fee` can rebuild and
+/// re-sign it with a higher fee (RBF) without double-spending its inputs.
+#[derive(Clone, PartialEq, Eq, Debug, BorshSerialize, BorshDeserialize)]
+pub struct InFlightTransfer {
+    pub tx_raw: Vec<u8>,
+    pub utxos: Vec<UTXO>,
+    pub fee_rate: u64,
+    pub nonces: Vec<u64>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord, BorshSerialize, BorshDeserialize)]
 pub enum Script {
     OpReturn(String),
     V0P2wpkh(String),
+    V1P2tr(String),
+    /// A deposit matched against the custody descriptor that is not one of
+    /// the connector's own `bitcoin_pk`/`bitcoin_taproot_pk` key-path
+    /// scripts (e.g. a `wsh`/multisig leg of the policy). Holds the raw
+    /// scriptPubkey bytes;
+    /// not currently spendable through `sign()`.
+    Custody(Vec<u8>),
 }
 
 impl Script {
     pub fn from_bytes(script_raw: Vec<u8>) -> Result<Script, &'static str> {
         const OP_RETURN: u8 = 0x6a;
 
+        if script_raw.is_empty() {
+            return Err("Incorrect script");
+        }
+
         if script_raw[0] == OP_RETURN {
-            return Ok(OpReturn(
-                String::from_utf8(script_raw[2..].to_vec()).unwrap(),
-            ));
+            return match String::from_utf8(script_raw.get(2..).unwrap_or(&[]).to_vec()) {
+                Ok(data) => Ok(OpReturn(data)),
+                Err(_) => Err("OP_RETURN payload is not valid UTF-8"),
+            };
         }
 
-        if script_raw[0] == 0x00 && script_raw[1] == 0x14 {
+        if script_raw.len() >= 2 && script_raw[0] == 0x00 && script_raw[1] == 0x14 {
             return Ok(Script::V0P2wpkh(hex::encode(&script_raw[2..])));
         }
 
+        if script_raw.len() >= 2 && script_raw[0] == 0x51 && script_raw[1] == 0x20 {
+            return Ok(Script::V1P2tr(hex::encode(&script_raw[2..])));
+        }
+
         return Err("Incorrect script");
     }
 }
@@ -10,10 +10,18 @@ pub struct FinTransferArgs {
     pub merkle_proof: Vec<H256>,
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SignatureScheme {
+    Ecdsa,
+    Schnorr,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SignRequest {
     pub payload: [u8; 32],
     pub path: String,
     pub key_version: u32,
+    pub scheme: SignatureScheme,
 }
@@ -1,5 +1,5 @@
 use btc_types::hash::H256;
-use crate::transaction::Script::OpReturn;
+use near_sdk::env;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Transaction {
@@ -7,6 +7,280 @@ pub struct Transaction {
     pub lock_time: u32,
     pub input: Vec<TxIn>,
     pub output: Vec<TxOut>,
+    /// Whether this transaction was parsed with the BIP144 marker/flag, kept
+    /// independent of `TxIn::witness` contents so a segwit-encoded
+    /// transaction whose inputs all happen to carry empty witness stacks
+    /// (e.g. still awaiting signing) still round-trips through `to_bytes` as
+    /// segwit instead of silently shrinking back to a legacy encoding.
+    pub is_segwit: bool,
+}
+
+fn double_sha256(bytes: &[u8]) -> H256 {
+    let digest = env::sha256(&env::sha256(bytes));
+    H256::try_from(digest).unwrap()
+}
+
+impl Transaction {
+    /// Identifies the transaction independent of witness data, as consensus
+    /// rules do: double-SHA256 of the legacy (non-witness) serialization.
+    pub fn txid(&self) -> H256 {
+        let mut bytes = Vec::new();
+        self.to_bytes_legacy(&mut bytes);
+        double_sha256(&bytes)
+    }
+
+    /// Identifies the transaction including witness data: double-SHA256 of
+    /// the full BIP144 serialization.
+    pub fn wtxid(&self) -> H256 {
+        let mut bytes = Vec::new();
+        self.to_bytes(&mut bytes);
+        double_sha256(&bytes)
+    }
+
+    fn to_bytes_legacy(&self, out: &mut Vec<u8>) {
+        self.version.to_bytes(out);
+        self.input.to_bytes(out);
+        self.output.to_bytes(out);
+        self.lock_time.to_bytes(out);
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockHeader {
+    pub version: i32,
+    pub prev_blockhash: H256,
+    pub merkle_root: H256,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub txdata: Vec<Transaction>,
+}
+
+impl BlockHeader {
+    /// Expands the compact `bits` (nBits) field into the 256-bit target a
+    /// valid block hash must not exceed, as a little-endian byte array
+    /// (matching the convention `spv_validate` compares the block hash in).
+    pub fn target(&self) -> Result<[u8; 32], &'static str> {
+        if self.bits & 0x00800000 != 0 {
+            return Err("Negative target");
+        }
+
+        let exponent = (self.bits >> 24) as i32;
+        let mantissa = (self.bits & 0x007fffff) as u64;
+        let mut target = [0u8; 32];
+
+        if exponent <= 3 {
+            let value = mantissa >> (8 * (3 - exponent));
+            target[0..8].copy_from_slice(&value.to_le_bytes());
+        } else {
+            let shift = (exponent - 3) as usize;
+            if shift > 29 {
+                return Err("Target exponent too large");
+            }
+            target[shift..shift + 3].copy_from_slice(&mantissa.to_le_bytes()[0..3]);
+        }
+
+        Ok(target)
+    }
+
+    fn header_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(80);
+        self.version.to_bytes(&mut out);
+        self.prev_blockhash.to_bytes(&mut out);
+        self.merkle_root.to_bytes(&mut out);
+        self.time.to_bytes(&mut out);
+        self.bits.to_bytes(&mut out);
+        self.nonce.to_bytes(&mut out);
+        out
+    }
+
+    /// Computes the header's double-SHA256 and checks it against `target()`.
+    /// Both are compared as little-endian 256-bit integers, matching how
+    /// Bitcoin consensus treats a digest's raw bytes for proof-of-work.
+    pub fn spv_validate(&self) -> Result<(), &'static str> {
+        let hash = double_sha256(&self.header_bytes());
+        let target = self.target()?;
+
+        for i in (0..32).rev() {
+            if hash.0[i] != target[i] {
+                return if hash.0[i] < target[i] {
+                    Ok(())
+                } else {
+                    Err("Block hash does not meet target difficulty")
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ConsensusDecoder for BlockHeader {
+    fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
+        Ok(BlockHeader {
+            version: i32::from_bytes(bytes, offset)?,
+            prev_blockhash: H256::from_bytes(bytes, offset)?,
+            merkle_root: H256::from_bytes(bytes, offset)?,
+            time: u32::from_bytes(bytes, offset)?,
+            bits: u32::from_bytes(bytes, offset)?,
+            nonce: u32::from_bytes(bytes, offset)?,
+        })
+    }
+}
+
+impl ConsensusDecoder for Block {
+    fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
+        Ok(Block {
+            header: BlockHeader::from_bytes(bytes, offset)?,
+            txdata: Vec::<Transaction>::from_bytes(bytes, offset)?,
+        })
+    }
+}
+
+// Smallest possible consensus encoding of a Transaction (no inputs, no
+// outputs): 4-byte version + 1-byte empty-input VarInt + 1-byte empty-output
+// VarInt + 4-byte locktime, used to reject an oversized declared txdata count
+// up front instead of looping until the buffer runs out.
+const MIN_TX_SIZE: usize = 4 + 1 + 1 + 4;
+
+impl ConsensusDecoder for Vec<Transaction> {
+    fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
+        let length = VarInt::from_bytes(bytes, offset)?.0 as usize;
+        let remaining = bytes.len().saturating_sub(*offset);
+        if length.saturating_mul(MIN_TX_SIZE) > remaining {
+            return Err("Declared transaction count exceeds remaining bytes");
+        }
+
+        let mut value = Vec::with_capacity(length.min(MAX_VEC_RESERVE));
+        for _ in 0..length {
+            value.push(Transaction::from_bytes(bytes, offset)?);
+        }
+        Ok(value)
+    }
+}
+
+impl ConsensusEncoder for BlockHeader {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.header_bytes());
+    }
+}
+
+/// An SPV proof (BIP37 `merkleblock`) that a set of transaction hashes are
+/// included under a block's merkle root, without needing the full block.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PartialMerkleTree {
+    pub total_transactions: u32,
+    pub hashes: Vec<H256>,
+    /// Traversal flags, one bit per visited tree node, packed LSB-first per
+    /// byte: `0` means "hash given directly", `1` means "descend further"
+    /// (or, at a leaf, "this hash is a match").
+    pub bits: Vec<u8>,
+}
+
+impl PartialMerkleTree {
+    fn calc_tree_width(&self, height: u32) -> u32 {
+        (self.total_transactions + (1 << height) - 1) >> height
+    }
+
+    fn tree_height(&self) -> u32 {
+        let mut height = 0;
+        while self.calc_tree_width(height) > 1 {
+            height += 1;
+        }
+        height
+    }
+
+    fn get_bit(&self, index: usize) -> Result<bool, &'static str> {
+        let byte = self.bits.get(index / 8).ok_or("Ran out of flag bits")?;
+        Ok((byte >> (index % 8)) & 1 == 1)
+    }
+
+    /// Reconstructs the tree from `hashes`/`bits`, returning the computed
+    /// merkle root and the `(txid, position)` pairs the flags marked as
+    /// matched. Errors on a malformed proof: inconsistent bit/hash counts,
+    /// or leftover flag bits that aren't zero padding.
+    pub fn extract_matches(&self) -> Result<(H256, Vec<(H256, usize)>), &'static str> {
+        if self.total_transactions == 0 {
+            return Err("No transactions");
+        }
+
+        let height = self.tree_height();
+        let mut bit_idx = 0usize;
+        let mut hash_idx = 0usize;
+        let mut matches = vec![];
+
+        let root = self.traverse(height, 0, &mut bit_idx, &mut hash_idx, &mut matches)?;
+
+        for i in bit_idx..self.bits.len() * 8 {
+            if self.get_bit(i)? {
+                return Err("Unexpected non-padding bit left over");
+            }
+        }
+        if hash_idx != self.hashes.len() {
+            return Err("Not all hashes were consumed");
+        }
+
+        Ok((root, matches))
+    }
+
+    fn traverse(
+        &self,
+        height: u32,
+        pos: u32,
+        bit_idx: &mut usize,
+        hash_idx: &mut usize,
+        matches: &mut Vec<(H256, usize)>,
+    ) -> Result<H256, &'static str> {
+        let bit = self.get_bit(*bit_idx)?;
+        *bit_idx += 1;
+
+        if height == 0 || !bit {
+            let hash = self.hashes.get(*hash_idx).ok_or("Ran out of hashes")?.clone();
+            *hash_idx += 1;
+            if height == 0 && bit {
+                matches.push((hash.clone(), pos as usize));
+            }
+            return Ok(hash);
+        }
+
+        let width = self.calc_tree_width(height - 1);
+        let left = self.traverse(height - 1, pos * 2, bit_idx, hash_idx, matches)?;
+        let right = if pos * 2 + 1 < width {
+            self.traverse(height - 1, pos * 2 + 1, bit_idx, hash_idx, matches)?
+        } else {
+            left.clone()
+        };
+
+        let mut concat = Vec::with_capacity(64);
+        concat.extend_from_slice(left.0.as_slice());
+        concat.extend_from_slice(right.0.as_slice());
+        Ok(double_sha256(&concat))
+    }
+}
+
+impl ConsensusDecoder for PartialMerkleTree {
+    fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
+        Ok(PartialMerkleTree {
+            total_transactions: u32::from_bytes(bytes, offset)?,
+            hashes: Vec::<H256>::from_bytes(bytes, offset)?,
+            bits: Vec::<u8>::from_bytes(bytes, offset)?,
+        })
+    }
+}
+
+impl ConsensusEncoder for PartialMerkleTree {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        self.total_transactions.to_bytes(out);
+        VarInt(self.hashes.len() as u64).to_bytes(out);
+        for hash in &self.hashes {
+            hash.to_bytes(out);
+        }
+        self.bits.to_bytes(out);
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -14,6 +288,9 @@ pub struct TxIn {
     pub previous_output: OutPoint,
     pub script_sig: Vec<u8>,
     pub sequence: u32,
+    /// SegWit (BIP144) witness stack for this input; empty for legacy
+    /// transactions or inputs that spend a non-witness output.
+    pub witness: Vec<Vec<u8>>,
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -22,27 +299,116 @@ pub struct TxOut {
     pub script_pubkey: Script,
 }
 
+/// A scriptPubkey/scriptSig held as its raw opcode bytes, so arbitrary
+/// scripts (P2PKH, P2SH, P2WSH, P2TR, ...) decode instead of erroring, not
+/// just the two patterns this crate used to special-case.
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-pub enum Script {
-    OpReturn(String),
-    V0P2wpkh(String),
+pub struct Script(pub Vec<u8>);
+
+const OP_PUSHDATA1: u8 = 0x4c;
+const OP_PUSHDATA2: u8 = 0x4d;
+const OP_PUSHDATA4: u8 = 0x4e;
+const OP_RETURN: u8 = 0x6a;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Instruction<'a> {
+    Op(u8),
+    PushBytes(&'a [u8]),
 }
 
-impl ConsensusDecoder for Script {
-    fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
-        let script_raw = Vec::<u8>::from_bytes(bytes, offset)?;
+pub struct Instructions<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
 
-        const OP_RETURN: u8 = 0x6a;
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, &'static str>;
 
-        if script_raw[0] == OP_RETURN {
-            return Ok(OpReturn(String::from_utf8(script_raw[2..].to_vec()).unwrap()));
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
         }
 
-        if script_raw[0] == 0x00 && script_raw[1] == 0x14 {
-            return Ok(Script::V0P2wpkh(hex::encode(&script_raw[2..])));
+        let opcode = self.data[self.pos];
+        self.pos += 1;
+
+        let push_len = match opcode {
+            0x01..=0x4b => opcode as usize,
+            OP_PUSHDATA1 => match self.take(1) {
+                Ok(len) => len[0] as usize,
+                Err(e) => return Some(Err(e)),
+            },
+            OP_PUSHDATA2 => match self.take(2) {
+                Ok(len) => u16::from_le_bytes(len.try_into().unwrap()) as usize,
+                Err(e) => return Some(Err(e)),
+            },
+            OP_PUSHDATA4 => match self.take(4) {
+                Ok(len) => u32::from_le_bytes(len.try_into().unwrap()) as usize,
+                Err(e) => return Some(Err(e)),
+            },
+            op => return Some(Ok(Instruction::Op(op))),
+        };
+
+        match self.take(push_len) {
+            Ok(bytes) => Some(Ok(Instruction::PushBytes(bytes))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<'a> Instructions<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        if self.pos + len > self.data.len() {
+            return Err("Not enough bytes for instruction");
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+impl Script {
+    pub fn instructions(&self) -> Instructions {
+        Instructions {
+            data: &self.0,
+            pos: 0,
+        }
+    }
+
+    pub fn is_op_return(&self) -> bool {
+        self.0.first() == Some(&OP_RETURN)
+    }
+
+    pub fn is_v0_p2wpkh(&self) -> bool {
+        self.0.len() == 22 && self.0[0] == 0x00 && self.0[1] == 0x14
+    }
+
+    /// The raw bytes pushed right after `OP_RETURN`, if this is an
+    /// `OP_RETURN` script with exactly one push; `None` otherwise. Returned
+    /// as raw bytes rather than assuming UTF-8, since OP_RETURN payloads are
+    /// arbitrary application data.
+    pub fn op_return_data(&self) -> Option<&[u8]> {
+        if !self.is_op_return() {
+            return None;
+        }
+        let mut instructions = Instructions {
+            data: &self.0[1..],
+            pos: 0,
+        };
+        let first = instructions.next();
+        if instructions.next().is_some() {
+            return None;
+        }
+        match first {
+            Some(Ok(Instruction::PushBytes(bytes))) => Some(bytes),
+            _ => None,
         }
+    }
+}
 
-        return Err("Incorrect script");
+impl ConsensusDecoder for Script {
+    fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
+        Ok(Script(Vec::<u8>::from_bytes(bytes, offset)?))
     }
 }
 
@@ -59,18 +425,46 @@ pub trait ConsensusDecoder: Sized {
     fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str>;
 }
 
+pub trait ConsensusEncoder {
+    fn to_bytes(&self, out: &mut Vec<u8>);
+}
+
 impl ConsensusDecoder for Transaction {
     fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
         let mut tx = Transaction{
             version: 0,
             lock_time: 0,
             input: vec![],
-            output: vec![]
+            output: vec![],
+            is_segwit: false,
         };
 
         tx.version = i32::from_bytes(bytes, offset)?;
+
+        // BIP144: a SegWit transaction carries a zero-length input count
+        // (the marker, 0x00) followed by a flag (0x01) right after the
+        // version, which a legacy transaction's real input count can never
+        // produce (it would mean zero inputs).
+        let is_segwit = *offset + 2 <= bytes.len() && bytes[*offset] == 0x00 && bytes[*offset + 1] == 0x01;
+        tx.is_segwit = is_segwit;
+        if is_segwit {
+            *offset += 2;
+        }
+
         tx.input = Vec::<TxIn>::from_bytes(bytes, offset)?;
         tx.output = Vec::<TxOut>::from_bytes(bytes, offset)?;
+
+        if is_segwit {
+            for input in tx.input.iter_mut() {
+                let witness_count = VarInt::from_bytes(bytes, offset)?.0 as usize;
+                let mut witness = Vec::with_capacity(witness_count.min(MAX_VEC_RESERVE));
+                for _ in 0..witness_count {
+                    witness.push(Vec::<u8>::from_bytes(bytes, offset)?);
+                }
+                input.witness = witness;
+            }
+        }
+
         tx.lock_time = u32::from_bytes(bytes, offset)?;
 
         Ok(tx)
@@ -138,6 +532,7 @@ impl ConsensusDecoder for TxIn {
             previous_output: OutPoint { txid: H256::default(), vout: 0 },
             script_sig: vec![],
             sequence: 0,
+            witness: vec![],
         };
         txinput.previous_output = OutPoint::from_bytes(bytes, offset)?;
         txinput.script_sig = Vec::<u8>::from_bytes(bytes, offset)?;
@@ -158,11 +553,28 @@ impl ConsensusDecoder for TxOut {
     }
 }
 
+// Smallest possible consensus encoding of a TxIn/TxOut (empty scriptSig /
+// scriptPubkey), used to reject an oversized declared count up front instead
+// of trusting it to size an allocation or bound a loop.
+const MIN_TXIN_SIZE: usize = 36 /* OutPoint */ + 1 /* empty script VarInt */ + 4 /* sequence */;
+const MIN_TXOUT_SIZE: usize = 8 /* value */ + 1 /* empty script VarInt */;
+
+// Caps any single up-front `Vec::with_capacity` reservation so a crafted
+// VarInt count (e.g. close to u64::MAX) can't force a huge allocation before
+// the per-element bounds checks ever run; the vector still grows normally
+// past this if the input legitimately contains more elements.
+const MAX_VEC_RESERVE: usize = 4096;
+
 impl ConsensusDecoder for Vec<TxIn> {
     fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
         let length = VarInt::from_bytes(bytes, offset)?.0 as usize;
-        let mut value = vec![];
-        for i in 0..length {
+        let remaining = bytes.len().saturating_sub(*offset);
+        if length.saturating_mul(MIN_TXIN_SIZE) > remaining {
+            return Err("Declared TxIn count exceeds remaining bytes");
+        }
+
+        let mut value = Vec::with_capacity(length.min(MAX_VEC_RESERVE));
+        for _ in 0..length {
             value.push(TxIn::from_bytes(bytes, offset)?);
         }
         Ok(value)
@@ -172,8 +584,13 @@ impl ConsensusDecoder for Vec<TxIn> {
 impl ConsensusDecoder for Vec<TxOut> {
     fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
         let length = VarInt::from_bytes(bytes, offset)?.0 as usize;
-        let mut value = vec![];
-        for i in 0..length {
+        let remaining = bytes.len().saturating_sub(*offset);
+        if length.saturating_mul(MIN_TXOUT_SIZE) > remaining {
+            return Err("Declared TxOut count exceeds remaining bytes");
+        }
+
+        let mut value = Vec::with_capacity(length.min(MAX_VEC_RESERVE));
+        for _ in 0..length {
             value.push(TxOut::from_bytes(bytes, offset)?);
         }
         Ok(value)
@@ -181,6 +598,24 @@ impl ConsensusDecoder for Vec<TxOut> {
 }
 
 
+const MIN_H256_SIZE: usize = 32;
+
+impl ConsensusDecoder for Vec<H256> {
+    fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
+        let length = VarInt::from_bytes(bytes, offset)?.0 as usize;
+        let remaining = bytes.len().saturating_sub(*offset);
+        if length.saturating_mul(MIN_H256_SIZE) > remaining {
+            return Err("Declared hash count exceeds remaining bytes");
+        }
+
+        let mut value = Vec::with_capacity(length.min(MAX_VEC_RESERVE));
+        for _ in 0..length {
+            value.push(H256::from_bytes(bytes, offset)?);
+        }
+        Ok(value)
+    }
+}
+
 impl ConsensusDecoder for OutPoint {
     fn from_bytes(bytes: &[u8], offset: &mut usize) -> Result<Self, &'static str> {
         let mut value = OutPoint{ txid: H256::from([0u8; 32]), vout: 0 };
@@ -249,6 +684,136 @@ impl ConsensusDecoder for VarInt {
     }
 }
 
+impl ConsensusEncoder for Transaction {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        if !self.is_segwit {
+            return self.to_bytes_legacy(out);
+        }
+
+        self.version.to_bytes(out);
+        out.push(0x00);
+        out.push(0x01);
+        self.input.to_bytes(out);
+        self.output.to_bytes(out);
+        for input in &self.input {
+            VarInt(input.witness.len() as u64).to_bytes(out);
+            for item in &input.witness {
+                item.to_bytes(out);
+            }
+        }
+        self.lock_time.to_bytes(out);
+    }
+}
+
+impl ConsensusEncoder for i32 {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ConsensusEncoder for u8 {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ConsensusEncoder for u16 {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ConsensusEncoder for u32 {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ConsensusEncoder for u64 {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ConsensusEncoder for TxIn {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        self.previous_output.to_bytes(out);
+        self.script_sig.to_bytes(out);
+        self.sequence.to_bytes(out);
+    }
+}
+
+impl ConsensusEncoder for TxOut {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        self.value.to_bytes(out);
+        self.script_pubkey.to_bytes(out);
+    }
+}
+
+impl ConsensusEncoder for Script {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        self.0.to_bytes(out);
+    }
+}
+
+impl ConsensusEncoder for Vec<TxIn> {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        VarInt(self.len() as u64).to_bytes(out);
+        for input in self {
+            input.to_bytes(out);
+        }
+    }
+}
+
+impl ConsensusEncoder for Vec<TxOut> {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        VarInt(self.len() as u64).to_bytes(out);
+        for output in self {
+            output.to_bytes(out);
+        }
+    }
+}
+
+impl ConsensusEncoder for OutPoint {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        self.txid.to_bytes(out);
+        self.vout.to_bytes(out);
+    }
+}
+
+impl ConsensusEncoder for H256 {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.0.as_slice());
+    }
+}
+
+impl ConsensusEncoder for Vec<u8> {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        VarInt(self.len() as u64).to_bytes(out);
+        out.extend_from_slice(self);
+    }
+}
+
+impl ConsensusEncoder for VarInt {
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        match self.0 {
+            0..=0xFC => out.push(self.0 as u8),
+            0xFD..=0xFFFF => {
+                out.push(0xFD);
+                (self.0 as u16).to_bytes(out);
+            }
+            0x10000..=0xFFFFFFFF => {
+                out.push(0xFE);
+                (self.0 as u32).to_bytes(out);
+            }
+            _ => {
+                out.push(0xFF);
+                self.0.to_bytes(out);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -260,4 +825,110 @@ mod tests {
 
         println!("{:?}", tx);
     }
+
+    #[test]
+    fn test_round_trip_tx() {
+        let raw_tx = vec![2, 0, 0, 0, 1, 146, 97, 87, 240, 48, 14, 73, 34, 141, 7, 70, 93, 114, 66, 33, 225, 162, 61, 65, 121, 144, 125, 23, 135, 76, 73, 173, 138, 39, 187, 4, 2, 1, 0, 0, 0, 0, 255, 255, 255, 255, 3, 44, 1, 0, 0, 0, 0, 0, 0, 22, 0, 20, 57, 110, 118, 95, 63, 217, 155, 137, 76, 174, 167, 233, 46, 187, 109, 135, 100, 174, 92, 221, 220, 5, 0, 0, 0, 0, 0, 0, 22, 0, 20, 171, 25, 243, 146, 206, 8, 220, 194, 181, 209, 37, 38, 57, 134, 222, 74, 165, 156, 95, 221, 0, 0, 0, 0, 0, 0, 0, 0, 17, 106, 15, 72, 101, 108, 108, 111, 44, 32, 66, 105, 116, 99, 111, 105, 110, 33, 0, 0, 0, 0];
+        let tx = Transaction::from_bytes(&raw_tx, &mut 0).unwrap();
+
+        let mut encoded = Vec::new();
+        tx.to_bytes(&mut encoded);
+
+        assert_eq!(encoded, raw_tx);
+    }
+
+    #[test]
+    fn test_decode_and_round_trip_segwit_tx() {
+        // Hand-built BIP144 tx: 1 input carrying a 2-item witness stack
+        // (signature + pubkey) spending a P2WPKH output.
+        let raw_tx = vec![
+            1, 0, 0, 0, 0, 1, 1, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+            170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+            170, 170, 170, 0, 0, 0, 0, 0, 255, 255, 255, 255, 1, 64, 66, 15, 0, 0, 0, 0, 0, 22,
+            0, 20, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 2, 70,
+            48, 68, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 33, 2, 17, 17, 17, 17, 17, 17, 17, 17,
+            17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17, 17,
+            17, 17, 17, 0, 0, 0, 0,
+        ];
+
+        let tx = Transaction::from_bytes(&raw_tx, &mut 0).unwrap();
+
+        assert!(tx.is_segwit);
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].witness.len(), 2);
+        assert_eq!(tx.input[0].witness[0].len(), 70);
+        assert_eq!(tx.input[0].witness[1].len(), 33);
+
+        let mut encoded = Vec::new();
+        tx.to_bytes(&mut encoded);
+        assert_eq!(encoded, raw_tx);
+    }
+
+    #[test]
+    fn test_segwit_tx_with_empty_witness_round_trips_as_segwit() {
+        // A segwit-marked transaction whose input carries a zero-length
+        // witness stack (valid BIP144 encoding, e.g. an unsigned tx awaiting
+        // signing) must still re-encode with the marker/flag/witness-count
+        // bytes, not silently collapse to a smaller legacy serialization.
+        let raw_tx = vec![
+            1, 0, 0, 0, 0, 1, 1, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+            170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+            170, 170, 0, 0, 0, 0, 0, 255, 255, 255, 255, 1, 64, 66, 15, 0, 0, 0, 0, 0, 22, 0, 20,
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 0, 0, 0, 0, 0,
+        ];
+
+        let tx = Transaction::from_bytes(&raw_tx, &mut 0).unwrap();
+
+        assert!(tx.is_segwit);
+        assert!(tx.input[0].witness.is_empty());
+
+        let mut encoded = Vec::new();
+        tx.to_bytes(&mut encoded);
+        assert_eq!(encoded, raw_tx);
+    }
+
+    #[test]
+    fn test_block_header_target() {
+        // Bitcoin genesis block's nBits (difficulty 1): target is
+        // 0x00000000FFFF0000000000000000000000000000000000000000000000000000
+        // big-endian, i.e. bytes 26 and 27 set (little-endian) and the rest
+        // zero.
+        let header = BlockHeader {
+            version: 1,
+            prev_blockhash: H256::from([0u8; 32]),
+            merkle_root: H256::from([0u8; 32]),
+            time: 0,
+            bits: 0x1d00ffff,
+            nonce: 0,
+        };
+
+        let mut expected = [0u8; 32];
+        expected[26] = 0xff;
+        expected[27] = 0xff;
+
+        assert_eq!(header.target().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_partial_merkle_tree_extract_matches() {
+        // Two-leaf tree, leaf 0 matches: bits (LSB-first) are
+        // [root=visit, left=visit, right=no-match] packed as 0b011 = 3.
+        let hash0 = H256::from([1u8; 32]);
+        let hash1 = H256::from([2u8; 32]);
+        let tree = PartialMerkleTree {
+            total_transactions: 2,
+            hashes: vec![hash0.clone(), hash1.clone()],
+            bits: vec![0b011],
+        };
+
+        let (root, matches) = tree.extract_matches().unwrap();
+
+        let mut concat = Vec::new();
+        concat.extend_from_slice(hash0.0.as_slice());
+        concat.extend_from_slice(hash1.0.as_slice());
+        assert_eq!(root, double_sha256(&concat));
+        assert_eq!(matches, vec![(hash0, 0)]);
+    }
 }